@@ -1,16 +1,12 @@
 #[derive(Debug, PartialEq)]
 pub struct Key(pub String);
 
-impl std::fmt::Display for Key {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
 #[derive(Debug, PartialEq)]
 pub enum Value {
     String(std::string::String),
     Number(std::string::String),
+    Bool(bool),
+    Null,
     Object(Vec<Pair>),
     Array(Vec<Value>),
 }
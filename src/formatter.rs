@@ -1,31 +1,101 @@
 use crate::ast::{Pair, Value};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentUnit {
+    Spaces(usize),
+    Tabs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatOptions {
+    pub indent: IndentUnit,
+    pub compact: bool,
+    // when formatting a stream of several top-level values, emit one
+    // compact document per line instead of separating the formatted
+    // documents with a blank line
+    pub ndjson: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent: IndentUnit::Spaces(4),
+            compact: false,
+            ndjson: false,
+        }
+    }
+}
+
 pub struct Formatter {
     depth: usize,
+    options: FormatOptions,
 }
 
 impl Formatter {
-    pub fn new() -> Self {
-        Self { depth: 0 }
+    pub fn with_options(options: FormatOptions) -> Self {
+        Self { depth: 0, options }
+    }
+    fn indent_unit(&self) -> String {
+        match self.options.indent {
+            IndentUnit::Spaces(n) => " ".repeat(n),
+            IndentUnit::Tabs => String::from("\t"),
+        }
     }
     fn indent(&self) -> String {
-        " ".repeat(4).repeat(self.depth)
+        self.indent_unit().repeat(self.depth)
     }
     fn format_string(&self, s: String) -> String {
-        format!("\"{}\"", s)
+        let mut out = String::with_capacity(s.len() + 2);
+        out.push('"');
+        for ch in s.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\u{0008}' => out.push_str("\\b"),
+                '\u{000C}' => out.push_str("\\f"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+                ch => out.push(ch),
+            }
+        }
+        out.push('"');
+        out
     }
     fn format_number(&self, num: String) -> String {
         num
     }
+    fn format_bool(&self, b: bool) -> String {
+        b.to_string()
+    }
+    fn format_null(&self) -> String {
+        String::from("null")
+    }
     fn format_object(&mut self, pairs: Vec<Pair>) -> String {
         if pairs.is_empty() {
             return String::from("{}");
         }
+        if self.options.compact {
+            let inner = pairs
+                .into_iter()
+                .map(|p| format!("{}:{}", self.format_string(p.key.0), self.format(p.value)))
+                .collect::<Vec<String>>()
+                .join(",");
+            return format!("{{{}}}", inner);
+        }
         let open_brace = '{';
         self.depth += 1;
         let inner = pairs
             .into_iter()
-            .map(|p| format!("{}\"{}\": {}", self.indent(), p.key, self.format(p.value)))
+            .map(|p| {
+                format!(
+                    "{}{}: {}",
+                    self.indent(),
+                    self.format_string(p.key.0),
+                    self.format(p.value)
+                )
+            })
             .collect::<Vec<String>>()
             .join(",\n");
         self.depth -= 1;
@@ -36,6 +106,14 @@ impl Formatter {
         if values.is_empty() {
             return String::from("[]");
         }
+        if self.options.compact {
+            let inner = values
+                .into_iter()
+                .map(|v| self.format(v))
+                .collect::<Vec<String>>()
+                .join(",");
+            return format!("[{}]", inner);
+        }
         let open_bracket = '[';
         self.depth += 1;
         let inner = values
@@ -51,10 +129,39 @@ impl Formatter {
         match v {
             Value::String(s) => self.format_string(s),
             Value::Number(num) => self.format_number(num),
+            Value::Bool(b) => self.format_bool(b),
+            Value::Null => self.format_null(),
             Value::Object(pairs) => self.format_object(pairs),
             Value::Array(values) => self.format_array(values),
         }
     }
+    // formats several top-level documents (NDJSON or concatenated JSON),
+    // one compact document per line in `ndjson` mode, otherwise each
+    // formatted with the other options and separated by a blank line
+    pub fn format_stream(&mut self, values: Vec<Value>) -> String {
+        if self.options.ndjson {
+            values
+                .into_iter()
+                .map(|v| {
+                    let options = FormatOptions {
+                        compact: true,
+                        ..self.options
+                    };
+                    Formatter::with_options(options).format(v)
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        } else {
+            values
+                .into_iter()
+                .map(|v| {
+                    self.depth = 0;
+                    self.format(v)
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n")
+        }
+    }
 }
 
 #[cfg(test)]
@@ -66,7 +173,14 @@ mod tests {
     fn format(text: &str) -> String {
         let mut p = Parser::new(Cursor::new(text));
         let v = p.parse_value().unwrap();
-        let mut f = Formatter::new();
+        let mut f = Formatter::with_options(FormatOptions::default());
+        f.format(v)
+    }
+
+    fn format_with(text: &str, options: FormatOptions) -> String {
+        let mut p = Parser::new(Cursor::new(text));
+        let v = p.parse_value().unwrap();
+        let mut f = Formatter::with_options(options);
         f.format(v)
     }
 
@@ -82,6 +196,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_format_string_escape() {
+        #[rustfmt::skip]
+        let tests = vec![
+            (r#""a\"b""#, r#""a\"b""#),
+            (r#""a\\b""#, r#""a\\b""#),
+            (r#""a\bb""#, r#""a\bb""#),
+            (r#""a\fb""#, r#""a\fb""#),
+            (r#""a\nb""#, r#""a\nb""#),
+            (r#""a\rb""#, r#""a\rb""#),
+            (r#""a\tb""#, r#""a\tb""#),
+            (r#""\u0001""#, r#""\u0001""#), // generic \u00XX control character path
+            (r#""ab""#, r#""ab""#),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format(input), String::from(expected));
+        }
+    }
+
     #[test]
     fn test_format_number() {
         #[rustfmt::skip]
@@ -94,6 +227,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_format_bool_and_null() {
+        #[rustfmt::skip]
+        let tests = vec![
+            ("true", "true"),
+            ("false", "false"),
+            ("null", "null"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format(input), String::from(expected));
+        }
+    }
+
     #[test]
     fn test_format_object() {
         #[rustfmt::skip]
@@ -141,6 +287,27 @@ r#"{
         }
     }
 
+    #[test]
+    fn test_format_object_key_escape() {
+        #[rustfmt::skip]
+        let tests = vec![
+            (r#"{"a\"b": 1}"#, "{\n    \"a\\\"b\": 1\n}"),
+            (r#"{"a\nb": 1}"#, "{\n    \"a\\nb\": 1\n}"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format(input), String::from(expected));
+        }
+
+        let options = FormatOptions {
+            compact: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_with(r#"{"a\"b": 1}"#, options),
+            String::from("{\"a\\\"b\":1}")
+        );
+    }
+
     #[test]
     fn test_format_array() {
         #[rustfmt::skip]
@@ -185,4 +352,63 @@ r#"[
             assert_eq!(format(input), String::from(expected));
         }
     }
+
+    #[test]
+    fn test_format_with_two_space_indent() {
+        let options = FormatOptions {
+            indent: IndentUnit::Spaces(2),
+            ..FormatOptions::default()
+        };
+        let actual = format_with(r#"{"a":123,"b":{"c":45}}"#, options);
+        let expected = r#"{
+  "a": 123,
+  "b": {
+    "c": 45
+  }
+}"#;
+        assert_eq!(actual, String::from(expected));
+    }
+
+    #[test]
+    fn test_format_with_tabs() {
+        let options = FormatOptions {
+            indent: IndentUnit::Tabs,
+            ..FormatOptions::default()
+        };
+        let actual = format_with(r#"{"a":[1,2]}"#, options);
+        let expected = "{\n\t\"a\": [\n\t\t1,\n\t\t2\n\t]\n}";
+        assert_eq!(actual, String::from(expected));
+    }
+
+    #[test]
+    fn test_format_compact() {
+        let options = FormatOptions {
+            compact: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_with(r#"{"a": 1, "b": [2, 3]}"#, options),
+            String::from(r#"{"a":1,"b":[2,3]}"#)
+        );
+    }
+
+    #[test]
+    fn test_format_stream() {
+        let mut p = Parser::new(Cursor::new("1 2 3"));
+        let values = p.parse_stream().unwrap();
+        let mut f = Formatter::with_options(FormatOptions::default());
+        assert_eq!(f.format_stream(values), "1\n\n2\n\n3");
+    }
+
+    #[test]
+    fn test_format_stream_ndjson() {
+        let mut p = Parser::new(Cursor::new(r#"{"a": 1} {"b": 2}"#));
+        let values = p.parse_stream().unwrap();
+        let options = FormatOptions {
+            ndjson: true,
+            ..FormatOptions::default()
+        };
+        let mut f = Formatter::with_options(options);
+        assert_eq!(f.format_stream(values), "{\"a\":1}\n{\"b\":2}");
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 mod ast;
 mod formatter;
-use formatter::Formatter;
+use formatter::{FormatOptions, Formatter, IndentUnit};
 mod parser;
 use parser::Parser;
 use std::fs::File;
@@ -14,10 +14,12 @@ use clap::App;
 fn main() -> Result<()> {
     let yaml = load_yaml!("main.yml");
     let matches = App::from_yaml(yaml).get_matches();
+    let options = parse_format_options(&matches)?;
+    let allow_duplicate_keys = matches.is_present("allow_duplicate_keys");
     if let Some(path) = matches.value_of_os("json_file") {
         let f = File::open(path)?;
         let reader = BufReader::new(f);
-        let result = format(reader)?;
+        let result = format(reader, options, allow_duplicate_keys)?;
         if matches.is_present("in_place") {
             let mut f = File::create(path)?;
             writeln!(f, "{}", result)?;
@@ -27,13 +29,34 @@ fn main() -> Result<()> {
     } else {
         let stdin = stdin();
         let reader = stdin.lock();
-        let result = format(reader)?;
+        let result = format(reader, options, allow_duplicate_keys)?;
         println!("{}", result);
     }
     Ok(())
 }
 
-fn format<R: BufRead>(reader: R) -> Result<String> {
-    let v = Parser::new(reader).parse_value()?;
-    Ok(Formatter::new().format(v))
+fn parse_format_options(matches: &clap::ArgMatches) -> Result<FormatOptions> {
+    let indent = if matches.is_present("tabs") {
+        IndentUnit::Tabs
+    } else if let Some(n) = matches.value_of("indent") {
+        IndentUnit::Spaces(n.parse()?)
+    } else {
+        IndentUnit::Spaces(4)
+    };
+    Ok(FormatOptions {
+        indent,
+        compact: matches.is_present("compact"),
+        ndjson: matches.is_present("ndjson"),
+    })
+}
+
+fn format<R: BufRead>(
+    reader: R,
+    options: FormatOptions,
+    allow_duplicate_keys: bool,
+) -> Result<String> {
+    let values = Parser::new(reader)
+        .allow_duplicate_keys(allow_duplicate_keys)
+        .parse_stream()?;
+    Ok(Formatter::with_options(options).format_stream(values))
 }
@@ -1,5 +1,6 @@
 use crate::ast::{Key, Pair, Value};
 use anyhow::{anyhow, bail, ensure, Result};
+use std::collections::HashMap;
 use std::io::{BufRead, Lines};
 
 pub struct Parser<R> {
@@ -7,26 +8,37 @@ pub struct Parser<R> {
     lines: Lines<R>,
     line: Option<Vec<char>>,
     line_number: usize,
+    allow_duplicate_keys: bool,
 }
 
 #[derive(Debug)]
 pub struct ParseError {
     message: String,
     line_number: usize,
+    column: usize,
+    line: String,
 }
 
 impl ParseError {
-    pub fn new(message: String, line_number: usize) -> Self {
+    pub fn new(message: String, line_number: usize, column: usize, line: String) -> Self {
         Self {
             message,
             line_number,
+            column,
+            line,
         }
     }
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "error at line {}: {}", self.line_number, self.message)
+        writeln!(
+            f,
+            "error at line {}, column {}: {}",
+            self.line_number, self.column, self.message
+        )?;
+        writeln!(f, "  {}", self.line)?;
+        write!(f, "  {}^", " ".repeat(self.column.saturating_sub(1)))
     }
 }
 
@@ -39,8 +51,15 @@ impl<R: BufRead> Parser<R> {
             lines,
             line: line.map(|l| l.chars().collect()),
             line_number: 1,
+            allow_duplicate_keys: false,
         }
     }
+    // by default, a repeated object key is a parse error; opting in makes
+    // the last occurrence win, matching many lenient JSON implementations
+    pub fn allow_duplicate_keys(mut self, allow: bool) -> Self {
+        self.allow_duplicate_keys = allow;
+        self
+    }
     fn succ(&mut self) {
         self.pos += 1;
         while let Some(line) = self.line.as_ref() {
@@ -57,6 +76,19 @@ impl<R: BufRead> Parser<R> {
     fn get_cur_char(&self) -> Option<&char> {
         self.line.as_ref().and_then(|l| l.get(self.pos))
     }
+    // build a ParseError pointing at the current position, capturing the
+    // source line so the `Display` impl can render a caret under it
+    fn err(&self, message: impl Into<String>) -> ParseError {
+        ParseError::new(
+            message.into(),
+            self.line_number,
+            self.pos + 1,
+            self.line
+                .as_ref()
+                .map(|l| l.iter().collect())
+                .unwrap_or_default(),
+        )
+    }
     fn cur_char_is(&self, ch: char) -> bool {
         match self.get_cur_char() {
             Some(&c) => c == ch,
@@ -64,16 +96,12 @@ impl<R: BufRead> Parser<R> {
         }
     }
     fn expect_char(&self, expect: char) -> Result<()> {
-        let &actual = self.get_cur_char().ok_or(anyhow!(ParseError::new(
-            format!("expected `{}`", expect),
-            self.line_number
-        )))?;
+        let &actual = self
+            .get_cur_char()
+            .ok_or_else(|| anyhow!(self.err(format!("expected `{}`", expect))))?;
         ensure!(
             expect == actual,
-            ParseError::new(
-                format!("expected: `{}`, found: `{}`", expect, actual),
-                self.line_number
-            )
+            self.err(format!("expected: `{}`, found: `{}`", expect, actual))
         );
         Ok(())
     }
@@ -88,15 +116,111 @@ impl<R: BufRead> Parser<R> {
             self.succ();
         }
     }
+    fn parse_hex4(&mut self) -> Result<u32> {
+        let mut hex = String::with_capacity(4);
+        for _ in 0..4 {
+            match self.get_cur_char().copied() {
+                Some(ch) if ch.is_ascii_hexdigit() => {
+                    hex.push(ch);
+                    self.succ();
+                }
+                _ => bail!(self.err("invalid `\\u` escape: expected 4 hex digits")),
+            }
+        }
+        Ok(u32::from_str_radix(&hex, 16).unwrap())
+    }
     fn parse_inner_string(&mut self) -> Result<String> {
         self.consume_char('"')?; // left quotes
         let mut s = String::new();
-        while let Some(&ch) = self.get_cur_char() {
-            if ch == '"' {
-                break;
+        loop {
+            match self.get_cur_char().copied() {
+                None | Some('"') => break,
+                Some('\\') => {
+                    self.succ(); // consume backslash
+                    let escaped = self
+                        .get_cur_char()
+                        .copied()
+                        .ok_or_else(|| anyhow!(self.err("unexpected end of string after `\\`")))?;
+                    match escaped {
+                        '"' => {
+                            s.push('"');
+                            self.succ();
+                        }
+                        '\\' => {
+                            s.push('\\');
+                            self.succ();
+                        }
+                        '/' => {
+                            s.push('/');
+                            self.succ();
+                        }
+                        'b' => {
+                            s.push('\u{0008}');
+                            self.succ();
+                        }
+                        'f' => {
+                            s.push('\u{000C}');
+                            self.succ();
+                        }
+                        'n' => {
+                            s.push('\n');
+                            self.succ();
+                        }
+                        'r' => {
+                            s.push('\r');
+                            self.succ();
+                        }
+                        't' => {
+                            s.push('\t');
+                            self.succ();
+                        }
+                        'u' => {
+                            self.succ(); // consume 'u'
+                            let high = self.parse_hex4()?;
+                            let code = if (0xD800..=0xDBFF).contains(&high) {
+                                self.consume_char('\\')?;
+                                self.consume_char('u')?;
+                                let low = self.parse_hex4()?;
+                                ensure!(
+                                    (0xDC00..=0xDFFF).contains(&low),
+                                    self.err("invalid low surrogate in `\\u` escape")
+                                );
+                                0x10000 + (high - 0xD800) * 0x400 + (low - 0xDC00)
+                            } else {
+                                high
+                            };
+                            let c = char::from_u32(code).ok_or_else(|| {
+                                anyhow!(self.err("invalid unicode scalar value in `\\u` escape"))
+                            })?;
+                            s.push(c);
+                        }
+                        other => {
+                            bail!(self.err(format!("invalid escape sequence: `\\{}`", other)))
+                        }
+                    }
+                }
+                Some(ch) => {
+                    ensure!(
+                        (ch as u32) >= 0x20,
+                        self.err(format!(
+                            "unescaped control character `\\u{:04x}` in string",
+                            ch as u32
+                        ))
+                    );
+                    s.push(ch);
+                    let line_before = self.line_number;
+                    self.succ();
+                    // `Lines` strips the line terminator before we ever see it, so a
+                    // raw newline inside an unterminated string would otherwise be
+                    // silently swallowed instead of rejected as a control character;
+                    // `self.line.is_some()` excludes true end-of-input, which is
+                    // reported separately as a missing closing quote
+                    ensure!(
+                        self.line_number == line_before || self.line.is_none(),
+                        self.err("unescaped control character `\\u000a` in string")
+                    );
+                }
             }
-            s.push(ch);
-            self.succ();
         }
         self.consume_char('"')?; // right quotes
         Ok(s)
@@ -109,24 +233,84 @@ impl<R: BufRead> Parser<R> {
         let s = self.parse_inner_string()?;
         Ok(Key(s))
     }
+    // number = -? (0 | [1-9][0-9]*) (.[0-9]+)? ([eE][+-]?[0-9]+)?
     fn parse_number(&mut self) -> Result<Value> {
         let mut num = String::new();
-        if let Some(&ch) = self.get_cur_char() {
-            if ch == '-' {
+        if self.cur_char_is('-') {
+            num.push('-');
+            self.succ();
+        }
+        match self.get_cur_char().copied() {
+            Some('0') => {
+                num.push('0');
+                self.succ();
+                if matches!(self.get_cur_char().copied(), Some(ch) if ch.is_ascii_digit()) {
+                    bail!(self.err("invalid number: leading zeros are not allowed"));
+                }
+            }
+            Some(ch) if ch.is_ascii_digit() => {
                 num.push(ch);
                 self.succ();
+                while matches!(self.get_cur_char().copied(), Some(ch) if ch.is_ascii_digit()) {
+                    num.push(self.get_cur_char().copied().unwrap());
+                    self.succ();
+                }
             }
+            _ => bail!(self.err("invalid number: expected a digit")),
         }
-        let valid = |ch: char| ch.is_digit(10) || ch == '.';
+        if self.cur_char_is('.') {
+            num.push('.');
+            self.succ();
+            let mut has_digit = false;
+            while matches!(self.get_cur_char().copied(), Some(ch) if ch.is_ascii_digit()) {
+                num.push(self.get_cur_char().copied().unwrap());
+                self.succ();
+                has_digit = true;
+            }
+            ensure!(
+                has_digit,
+                self.err("invalid number: expected a digit after `.`")
+            );
+            if self.cur_char_is('.') {
+                bail!(self.err("invalid number: unexpected second `.`"));
+            }
+        }
+        if matches!(self.get_cur_char().copied(), Some('e') | Some('E')) {
+            num.push(self.get_cur_char().copied().unwrap());
+            self.succ();
+            if matches!(self.get_cur_char().copied(), Some('+') | Some('-')) {
+                num.push(self.get_cur_char().copied().unwrap());
+                self.succ();
+            }
+            let mut has_digit = false;
+            while matches!(self.get_cur_char().copied(), Some(ch) if ch.is_ascii_digit()) {
+                num.push(self.get_cur_char().copied().unwrap());
+                self.succ();
+                has_digit = true;
+            }
+            ensure!(
+                has_digit,
+                self.err("invalid number: expected a digit in exponent")
+            );
+        }
+        Ok(Value::Number(num))
+    }
+    fn parse_literal(&mut self) -> Result<Value> {
+        let mut word = String::new();
         while let Some(&ch) = self.get_cur_char() {
-            if valid(ch) {
-                num.push(ch);
+            if ch.is_alphabetic() {
+                word.push(ch);
                 self.succ();
             } else {
                 break;
             }
         }
-        Ok(Value::Number(num))
+        match word.as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            "null" => Ok(Value::Null),
+            _ => bail!(self.err(format!("invalid token: `{}`", word))),
+        }
     }
     fn parse_object(&mut self) -> Result<Value> {
         self.expect_char('{')?;
@@ -136,7 +320,8 @@ impl<R: BufRead> Parser<R> {
             self.succ();
             return Ok(Value::Object(Vec::new()));
         }
-        let mut pairs = vec![];
+        let mut pairs: Vec<Pair> = vec![];
+        let mut seen: HashMap<String, usize> = HashMap::new();
         loop {
             self.skip_whitespace();
             let key = self.parse_string_key()?;
@@ -144,18 +329,23 @@ impl<R: BufRead> Parser<R> {
             self.consume_char(':')?;
             let value = self.parse_value()?;
             self.skip_whitespace();
-            pairs.push(Pair::new(key, value));
+            if let Some(&index) = seen.get(&key.0) {
+                ensure!(
+                    self.allow_duplicate_keys,
+                    self.err(format!("duplicate key: `{}`", key.0))
+                );
+                pairs[index] = Pair::new(key, value);
+            } else {
+                seen.insert(key.0.clone(), pairs.len());
+                pairs.push(Pair::new(key, value));
+            }
             match self.get_cur_char() {
                 Some(',') => self.succ(),
                 Some('}') => break,
-                Some(&other) => bail!(ParseError::new(
-                    format!("expected: `,` or `}}`, found: `{}`", other),
-                    self.line_number
-                )),
-                None => bail!(ParseError::new(
-                    "expected `,` or `}}`".to_string(),
-                    self.line_number
-                )),
+                Some(&other) => {
+                    bail!(self.err(format!("expected: `,` or `}}`, found: `{}`", other)))
+                }
+                None => bail!(self.err("expected `,` or `}`")),
             }
         }
         self.expect_char('}')?;
@@ -177,19 +367,33 @@ impl<R: BufRead> Parser<R> {
             match self.get_cur_char() {
                 Some(',') => self.succ(),
                 Some(']') => break,
-                Some(&other) => bail!(ParseError::new(
-                    format!("expected: `,` or `]`, found: {}", other),
-                    self.line_number,
-                )),
-                None => bail!(ParseError::new(
-                    "expected `,` or `]`".to_string(),
-                    self.line_number
-                )),
+                Some(&other) => {
+                    bail!(self.err(format!("expected: `,` or `]`, found: {}", other)))
+                }
+                None => bail!(self.err("expected `,` or `]`")),
             }
         }
         self.consume_char(']')?;
         Ok(Value::Array(values))
     }
+    // parses as many whitespace-separated top-level values as the input
+    // holds, e.g. NDJSON or several concatenated JSON documents
+    pub fn parse_stream(&mut self) -> Result<Vec<Value>> {
+        let mut values = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.line.is_none() {
+                break;
+            }
+            if self.get_cur_char().is_none() {
+                // an empty first line leaves no current char; step past it
+                self.succ();
+                continue;
+            }
+            values.push(self.parse_value()?);
+        }
+        Ok(values)
+    }
     pub fn parse_value(&mut self) -> Result<Value> {
         self.skip_whitespace();
         match self.get_cur_char() {
@@ -197,14 +401,9 @@ impl<R: BufRead> Parser<R> {
             Some('[') => self.parse_array(),
             Some('"') => self.parse_string_value(),
             Some(&ch) if (ch == '-' || ch.is_digit(10)) => self.parse_number(),
-            Some(&other) => bail!(ParseError::new(
-                format!("invalid token: `{}`", other),
-                self.line_number,
-            )),
-            None => bail!(ParseError::new(
-                "no token found".to_string(),
-                self.line_number
-            )),
+            Some(&ch) if ch.is_alphabetic() => self.parse_literal(),
+            Some(&other) => bail!(self.err(format!("invalid token: `{}`", other))),
+            None => bail!(self.err("no token found")),
         }
     }
 }
@@ -240,7 +439,34 @@ mod tests {
         test(r#""""#, ""); // ""
         test(r#""   ""#, "   "); // "   "
         test(r#""abc de f""#, "abc de f"); // "abc de f"
-        test(r#""abc\nde f""#, "abc\\nde f"); // "abc\nde f"
+        test(r#""abc\nde f""#, "abc\nde f"); // "abc\nde f"
+        test(r#""a\"b""#, "a\"b"); // "a\"b"
+        test(r#""\\ \/ \b \f \n \r \t""#, "\\ / \u{8} \u{c} \n \r \t");
+        test(r#""あい""#, "あい");
+        test(r#""\u3042\u3044""#, "あい"); // \u escape, BMP
+        test(r#""\uD83D\uDE00""#, "😀"); // surrogate pair
+    }
+
+    #[test]
+    fn test_ng_parse_string_escape() {
+        let test = |input: &str| {
+            let mut p = parser(input);
+            assert!(p.parse_string_value().is_err());
+        };
+        test(r#""\x""#); // unknown escape
+        test(r#""\u12""#); // too few hex digits
+        test(r#""\uzzzz""#); // not hex
+        test(r#""\uD800""#); // unpaired high surrogate
+    }
+
+    #[test]
+    fn test_ng_parse_string_control_char() {
+        let test = |input: &str| {
+            let mut p = parser(input);
+            assert!(p.parse_string_value().is_err());
+        };
+        test("\"a\nb\""); // literal newline, not `\n`
+        test("\"a\tb\""); // literal tab, not `\t`
     }
 
     #[test]
@@ -262,16 +488,51 @@ mod tests {
         };
         test("-123.45", "-123.45");
         test("-0", "-0");
-        test(".123", ".123");
-        test("-.123", "-.123");
-        test("123.", "123.");
-        test("000123", "000123");
+        test("0", "0");
+        test("1.5e10", "1.5e10");
+        test("1.5E+10", "1.5E+10");
+        test("1e-10", "1e-10");
+        test("123abc", "123"); // the trailing `abc` is left for the caller
+    }
+
+    #[test]
+    fn test_ng_parse_number() {
+        let test = |input: &str| {
+            let mut p = parser(input);
+            assert!(p.parse_number().is_err());
+        };
+        test(".123");
+        test("-.123");
+        test("123.");
+        test("000123");
+        test("1.23.45");
+        test("-");
+        test("-.");
+        test("1e");
+        test("1e+");
+    }
 
-        // TODO: 落ちてほしい
-        test("1.23.45", "1.23.45");
-        test("-", "-");
-        test("-.", "-.");
-        test("123abc", "123");
+    #[test]
+    fn test_parse_literal() {
+        let test = |input: &str, result: Value| {
+            let mut p = parser(input);
+            let v = p.parse_literal().unwrap();
+            assert_eq!(v, result);
+        };
+        test("true", Value::Bool(true));
+        test("false", Value::Bool(false));
+        test("null", Value::Null);
+    }
+
+    #[test]
+    fn test_ng_parse_literal() {
+        let test = |input: &str| {
+            let mut p = parser(input);
+            assert!(p.parse_literal().is_err());
+        };
+        test("True");
+        test("nul");
+        test("tru");
     }
 
     fn test_object<F>(v: Value, f: F)
@@ -338,6 +599,20 @@ mod tests {
         test(r#""a":123}"#); // "a":123}
         test(r#"{"a":123  "bc":"xyz"}"#); // missing comma
         test(r#"{"a"  123}"#); // missing colon
+        test(r#"{"a":1,"a":2}"#); // duplicate key
+    }
+
+    #[test]
+    fn test_parse_object_allow_duplicate_keys() {
+        let mut p = parser(r#"{"a":1,"b":2,"a":3}"#).allow_duplicate_keys(true);
+        let v: Value = p.parse_object().unwrap();
+        test_object(v, |pairs: Vec<Pair>| {
+            let expected = vec![
+                Pair::new(key("a"), number("3")),
+                Pair::new(key("b"), number("2")),
+            ];
+            assert_eq!(pairs, expected);
+        });
     }
 
     fn test_array<F>(v: Value, f: F)
@@ -456,4 +731,46 @@ r#"{
             4,
         );
     }
+
+    #[test]
+    fn test_parse_stream() {
+        let mut p = parser("1 true\n\"a\"\n{\"k\": 2}\n");
+        let values = p.parse_stream().unwrap();
+        let expected = vec![
+            number("1"),
+            Value::Bool(true),
+            string("a"),
+            Value::Object(vec![Pair::new(key("k"), number("2"))]),
+        ];
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_parse_stream_empty() {
+        let mut p = parser("   \n  ");
+        let values = p.parse_stream().unwrap();
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_error_display() {
+        #[rustfmt::skip]
+        let mut p = parser(
+r#"{
+"a": 123,
+"b"  45
+}"#,
+        );
+        let e = p
+            .parse_object()
+            .unwrap_err()
+            .downcast::<ParseError>()
+            .unwrap();
+        assert_eq!(e.line_number, 3);
+        assert_eq!(e.column, 6);
+        assert_eq!(
+            e.to_string(),
+            "error at line 3, column 6: expected: `:`, found: `4`\n  \"b\"  45\n       ^"
+        );
+    }
 }